@@ -10,12 +10,14 @@
 
 extern crate curl;
 extern crate env_logger;
+extern crate flate2;
 #[macro_use]
 extern crate lazy_static;
 #[macro_use]
 extern crate log;
 extern crate rustc_serialize;
 extern crate semver;
+extern crate tar;
 extern crate term;
 extern crate toml;
 extern crate threadpool;
@@ -23,15 +25,19 @@ extern crate num_cpus;
 extern crate tempdir;
 
 use curl::{http, ErrCode};
+use flate2::read::GzDecoder;
+use tar::Archive;
 use curl::http::Response as CurlHttpResponse;
 use rustc_serialize::json;
 use semver::Version;
+use std::cmp;
+use std::collections::HashSet;
 use std::convert::From;
 use std::env;
 use std::fmt;
 use std::fs::{self, File};
 use std::io::{self, Read, Write};
-use std::path::{PathBuf, Path};
+use std::path::{Component, PathBuf, Path};
 use std::process::Command;
 use std::str::Utf8Error;
 use std::string::FromUtf8Error;
@@ -42,12 +48,26 @@ use tempdir::TempDir;
 
 fn main() {
     env_logger::init().unwrap();
-    report_results(run());
-}
 
-fn run() -> Result<Vec<TestResult>, Error> {
-    let config = try!(get_config());
+    // Parsed up front, independent of the rest of `Config`, so the
+    // requested output format is still honored if building the config
+    // itself fails (e.g. a bad manifest) — a CI pipeline invoking
+    // `--message-format=json` should always get JSON back, never a
+    // human-formatted fallback line.
+    let message_format = match get_message_format() {
+        Ok(f) => f,
+        Err(e) => return report_results(MessageFormat::Human, Err(e))
+    };
+    set_quiet(message_format == MessageFormat::Json);
+
+    let config = match get_config() {
+        Ok(config) => config,
+        Err(e) => return report_results(message_format, Err(e))
+    };
+    report_results(message_format, run(config));
+}
 
+fn run(config: Config) -> Result<Vec<TestResult>, Error> {
     // Find all the crates on crates.io the depend on ours
     let rev_deps = try!(get_rev_deps(&config.crate_name));
 
@@ -76,8 +96,12 @@ fn run() -> Result<Vec<TestResult>, Error> {
 struct Config {
     manifest_path: PathBuf,
     crate_name: String,
+    our_version: Version,
     base_override: CrateOverride,
-    next_override: CrateOverride
+    next_override: CrateOverride,
+    targets: Vec<String>,
+    mode: Mode,
+    sandbox_image: Option<String>
 }
 
 #[derive(Clone)]
@@ -86,6 +110,23 @@ enum CrateOverride {
     Source(PathBuf)
 }
 
+// Whether a rev dep just needs to compile against our crate, or should
+// also have its own test suite run so behavioral regressions that still
+// typecheck get caught too.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Mode {
+    Build,
+    Test
+}
+
+// Controls whether results go to the terminal for a human, or as a
+// stream of JSON records for a CI pipeline to parse.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum MessageFormat {
+    Human,
+    Json
+}
+
 type VersionNumber = String;
 
 fn get_config() -> Result<Config, Error> {
@@ -94,15 +135,98 @@ fn get_config() -> Result<Config, Error> {
     let manifest = PathBuf::from(manifest);
     debug!("Using manifest {:?}", manifest);
 
+    let targets = try!(get_targets());
+    let mode = try!(get_mode());
+    let sandbox_image = try!(get_sandbox_image());
+
     let source_name = try!(get_crate_name(&manifest));
+    let our_version = try!(get_crate_version(&manifest));
     Ok(Config {
         manifest_path: manifest.clone(),
         crate_name: source_name,
+        our_version: our_version,
         base_override: CrateOverride::Default,
-        next_override: CrateOverride::Source(manifest)
+        next_override: CrateOverride::Source(manifest),
+        targets: targets,
+        mode: mode,
+        sandbox_image: sandbox_image
     })
 }
 
+// Reads `--sandbox-image <image>` from argv. When set, rev dep builds
+// run inside a container using this image instead of directly on the
+// host, for reproducible, isolated results with untrusted crates.
+fn get_sandbox_image() -> Result<Option<String>, Error> {
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--sandbox-image" {
+            return match args.next() {
+                Some(image) => Ok(Some(image)),
+                None => Err(Error::MissingArgument("--sandbox-image".to_string()))
+            };
+        }
+    }
+    Ok(None)
+}
+
+// Reads `--message-format=(human|json)` from argv, also accepting a
+// separate `--message-format json` token to stay consistent with how
+// `--mode` and `--target` are parsed. Defaults to `human`.
+fn get_message_format() -> Result<MessageFormat, Error> {
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg.starts_with("--message-format") {
+            let value = match arg.find('=') {
+                Some(eq) => arg[eq + 1..].to_string(),
+                None => match args.next() {
+                    Some(v) => v,
+                    None => return Err(Error::MissingArgument("--message-format".to_string()))
+                }
+            };
+            return match &*value {
+                "human" => Ok(MessageFormat::Human),
+                "json" => Ok(MessageFormat::Json),
+                _ => Err(Error::InvalidArgument("--message-format".to_string(), value))
+            };
+        }
+    }
+    Ok(MessageFormat::Human)
+}
+
+// Reads `--mode (build|test)` from argv. Defaults to `build`, which is
+// the tool's original behavior.
+fn get_mode() -> Result<Mode, Error> {
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--mode" {
+            return match args.next() {
+                Some(ref m) if m == "build" => Ok(Mode::Build),
+                Some(ref m) if m == "test" => Ok(Mode::Test),
+                Some(m) => Err(Error::InvalidArgument("--mode".to_string(), m)),
+                None => Err(Error::MissingArgument("--mode".to_string()))
+            };
+        }
+    }
+    Ok(Mode::Build)
+}
+
+// Pulls every `--target <triple>` pair out of argv. Repeatable, like
+// cargo's own --target flag, so a run can be checked against several
+// cross-compilation triples at once.
+fn get_targets() -> Result<Vec<String>, Error> {
+    let mut targets = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--target" {
+            match args.next() {
+                Some(t) => targets.push(t),
+                None => return Err(Error::MissingArgument("--target".to_string()))
+            }
+        }
+    }
+    Ok(targets)
+}
+
 fn get_crate_name(manifest_path: &Path) -> Result<String, Error> {
     let ref toml = try!(load_string(manifest_path));
     let mut parser = toml::Parser::new(toml);
@@ -130,6 +254,33 @@ fn get_crate_name(manifest_path: &Path) -> Result<String, Error> {
     }
 }
 
+fn get_crate_version(manifest_path: &Path) -> Result<Version, Error> {
+    let ref toml = try!(load_string(manifest_path));
+    let mut parser = toml::Parser::new(toml);
+    let toml = parser.parse();
+    let map = if toml.is_none() {
+        return Err(Error::TomlError(parser.errors))
+    } else {
+        toml.unwrap()
+    };
+
+    match map.get("package") {
+        Some(&toml::Value::Table(ref t)) => {
+            match t.get("version") {
+                Some(&toml::Value::String(ref s)) => {
+                    Ok(try!(Version::parse(s)))
+                }
+                _ => {
+                    Err(Error::ManifestName(PathBuf::from(manifest_path)))
+                }
+            }
+        }
+        _ => {
+            Err(Error::ManifestName(PathBuf::from(manifest_path)))
+        }
+    }
+}
+
 fn load_string(path: &Path) -> Result<String, Error> {
     let mut file = try!(File::open(path));
     let mut s = String::new();
@@ -147,17 +298,65 @@ fn crate_url(krate: &str, call: Option<&str>) -> String {
     }
 }
 
-fn get_rev_deps(crate_name: &str) -> Result<Vec<RevDepName>, Error> {
+// A candidate reverse dependency together with the version requirement
+// it places on *our* crate, as returned by the `dependencies` array of
+// the reverse_dependencies API. The requirement lets `run_test_local`
+// skip rev deps that could never resolve to the in-development version.
+#[derive(Debug, Clone)]
+struct RevDepCandidate {
+    name: RevDepName,
+    req: String
+}
+
+// crates.io paginates `reverse_dependencies`, so a popular crate's
+// dependents span many pages. Follow `page`/`per_page` until we've
+// collected `meta.total` of them.
+fn get_rev_deps(crate_name: &str) -> Result<Vec<RevDepCandidate>, Error> {
     status(&format!("downloading reverse deps for {}", crate_name));
-    let ref url = crate_url(crate_name, Some("reverse_dependencies"));
-    let ref body = try!(http_get_to_string(url));
-    let rev_deps = try!(parse_rev_deps(body));
+
+    let mut rev_deps = Vec::new();
+    let mut page = 1;
+    loop {
+        let ref url = format!("{}?page={}&per_page=100",
+                               crate_url(crate_name, Some("reverse_dependencies")),
+                               page);
+        let ref body = try!(http_get_to_string(url));
+        let (mut page_deps, total) = try!(parse_rev_deps(body));
+        let got_any = !page_deps.is_empty();
+        rev_deps.append(&mut page_deps);
+
+        if !got_any || rev_deps.len() >= total {
+            break;
+        }
+        page += 1;
+    }
+
+    let rev_deps = dedup_rev_deps(rev_deps);
 
     status(&format!("{} reverse deps", rev_deps.len()));
 
     Ok(rev_deps)
 }
 
+// `reverse_dependencies` returns one row per dependent *version*, not
+// per dependent crate, so a long-lived crate shows up many times.
+// `resolve_rev_dep_version` always resolves to each crate's current
+// latest version regardless of which row it came from, so testing
+// every row would just re-run the same rev dep repeatedly. Keep one
+// candidate per crate name, preferring the requirement from whichever
+// row crates.io listed first (its most recent dependent version).
+fn dedup_rev_deps(rev_deps: Vec<RevDepCandidate>) -> Vec<RevDepCandidate> {
+    let mut seen = HashSet::new();
+    let mut deduped = Vec::new();
+    for candidate in rev_deps {
+        if seen.insert(candidate.name.clone()) {
+            deduped.push(candidate);
+        }
+    }
+
+    deduped
+}
+
 fn http_get_to_string(url: &str) -> Result<String, Error> {
     Ok(try!(String::from_utf8(try!(http_get_bytes(url)))))
 }
@@ -188,27 +387,37 @@ fn http_get_bytes(url: &str) -> Result<Vec<u8>, Error> {
     Ok(resp.move_body())
 }
 
-fn parse_rev_deps(s: &str) -> Result<Vec<RevDepName>, Error> {
+fn parse_rev_deps(s: &str) -> Result<(Vec<RevDepCandidate>, usize), Error> {
     #[derive(RustcEncodable, RustcDecodable)]
     struct Response {
         dependencies: Vec<Dep>,
+        meta: Meta
     }
 
     #[derive(RustcEncodable, RustcDecodable)]
     struct Dep {
-        crate_id: String
+        crate_id: String,
+        req: String
+    }
+
+    #[derive(RustcEncodable, RustcDecodable)]
+    struct Meta {
+        total: usize
     }
 
     let decoded: Response = try!(json::decode(&s));
+    let total = decoded.meta.total;
 
-    fn depconv(d: Dep) -> RevDepName { d.crate_id }
+    fn depconv(d: Dep) -> RevDepCandidate {
+        RevDepCandidate { name: d.crate_id, req: d.req }
+    }
 
     let revdeps = decoded.dependencies.into_iter()
-        .map(depconv).collect();
+        .map(depconv).collect::<Vec<_>>();
 
     debug!("revdeps: {:?}", revdeps);
 
-    Ok(revdeps)
+    Ok((revdeps, total))
 }
 
 #[derive(Debug, Clone)]
@@ -225,28 +434,69 @@ struct TestResult {
 
 #[derive(Debug)]
 enum TestResultData {
-    Broken(CompileResult),
-    Regressed(CompileResult, CompileResult),
-    Pass(CompileResult, CompileResult),
+    Broken(Vec<TargetCompileResult>),
+    Regressed(Vec<TargetCompileResult>, Vec<TargetCompileResult>),
+    TestRegressed(Vec<TargetCompileResult>, Vec<TargetCompileResult>),
+    Pass(Vec<TargetCompileResult>, Vec<TargetCompileResult>),
+    Skipped(String),
     Error(Error),
 }
 
+impl TestResultData {
+    // Names of the targets whose `next` build failed where the `base`
+    // build for that same target succeeded. Empty for anything but
+    // `Regressed`/`TestRegressed`.
+    fn regressed_targets(&self) -> Vec<String> {
+        match *self {
+            TestResultData::Regressed(ref base, ref next) |
+            TestResultData::TestRegressed(ref base, ref next) => {
+                next.iter().filter(|n| n.failed())
+                    .filter(|n| !base.iter().any(|b| b.target == n.target && b.failed()))
+                    .map(|n| n.target_name().to_string())
+                    .collect()
+            }
+            _ => Vec::new()
+        }
+    }
+
+    // Names of the individual tests that went from passing to failing.
+    // Only populated for `TestRegressed`.
+    fn regressed_tests(&self) -> Vec<String> {
+        match *self {
+            TestResultData::TestRegressed(_, ref next) => {
+                next.iter()
+                    .filter_map(|n| n.tests.as_ref())
+                    .flat_map(|t| t.failing_tests.clone())
+                    .collect()
+            }
+            _ => Vec::new()
+        }
+    }
+}
+
 impl TestResult {
-    fn broken(rev_dep: RevDep, r: CompileResult) -> TestResult {
+    fn broken(rev_dep: RevDep, r: Vec<TargetCompileResult>) -> TestResult {
         TestResult {
             rev_dep: rev_dep,
             data: TestResultData::Broken(r)
         }
     }
 
-    fn regressed(rev_dep: RevDep, r1: CompileResult, r2: CompileResult) -> TestResult {
+    fn regressed(rev_dep: RevDep, r1: Vec<TargetCompileResult>, r2: Vec<TargetCompileResult>) -> TestResult {
         TestResult {
             rev_dep: rev_dep,
             data: TestResultData::Regressed(r1, r2)
         }
     }
 
-    fn pass(rev_dep: RevDep, r1: CompileResult, r2: CompileResult) -> TestResult {
+    fn test_regressed(rev_dep: RevDep, r1: Vec<TargetCompileResult>, r2: Vec<TargetCompileResult>) -> TestResult {
+        TestResult {
+            rev_dep: rev_dep,
+            data: TestResultData::TestRegressed(r1, r2)
+        }
+    }
+
+    fn pass(rev_dep: RevDep, r1: Vec<TargetCompileResult>, r2: Vec<TargetCompileResult>) -> TestResult {
         TestResult {
             rev_dep: rev_dep,
             data: TestResultData::Pass(r1, r2)
@@ -259,12 +509,21 @@ impl TestResult {
             data: TestResultData::Error(e)
         }
     }
+
+    fn skipped(rev_dep: RevDep, reason: String) -> TestResult {
+        TestResult {
+            rev_dep: rev_dep,
+            data: TestResultData::Skipped(reason)
+        }
+    }
     
     fn quick_str(&self) -> &'static str {
         match self.data {
             TestResultData::Broken(_) => "broken",
             TestResultData::Regressed(..) => "regressed",
+            TestResultData::TestRegressed(..) => "test-regressed",
             TestResultData::Pass(..) => "pass",
+            TestResultData::Skipped(_) => "skipped",
             TestResultData::Error(_) => "error"
         }
     }
@@ -273,7 +532,9 @@ impl TestResult {
         match self.data {
             TestResultData::Broken(_) => term::color::BRIGHT_YELLOW,
             TestResultData::Regressed(..) => term::color::BRIGHT_RED,
+            TestResultData::TestRegressed(..) => term::color::RED,
             TestResultData::Pass(..) => term::color::BRIGHT_GREEN,
+            TestResultData::Skipped(_) => term::color::BRIGHT_BLUE,
             TestResultData::Error(_) => term::color::BRIGHT_MAGENTA
         }
     }
@@ -312,55 +573,95 @@ fn new_result_receiver(rev_dep: RevDepName) -> (Sender<TestResult>, TestResultRe
 
 fn run_test(pool: &mut ThreadPool,
             config: Config,
-            rev_dep: RevDepName) -> TestResultReceiver {
-    let (result_tx, result_rx) = new_result_receiver(rev_dep.clone());
+            candidate: RevDepCandidate) -> TestResultReceiver {
+    let (result_tx, result_rx) = new_result_receiver(candidate.name.clone());
     pool.execute(move || {
-        let res = run_test_local(&config, rev_dep);
+        let res = run_test_local(&config, candidate);
         result_tx.send(res).unwrap();
     });
 
     return result_rx;
 }
 
-fn run_test_local(config: &Config, rev_dep: RevDepName) -> TestResult {
+fn run_test_local(config: &Config, candidate: RevDepCandidate) -> TestResult {
 
-    status(&format!("testing crate {}", rev_dep));
+    status(&format!("testing crate {}", candidate.name));
 
     // First, figure get the most recent version number
-    let rev_dep = match resolve_rev_dep_version(rev_dep.clone()) {
+    let rev_dep = match resolve_rev_dep_version(candidate.name.clone()) {
         Ok(r) => r,
         Err(e) => {
             let rev_dep = RevDep {
-                name: rev_dep,
+                name: candidate.name,
                 vers: Version::parse("0.0.0").unwrap()
             };
             return TestResult::error(rev_dep, e);
         }
     };
 
-    // TODO: Decide whether the version of our crate requested by the
-    // rev dep is semver-compatible with the in-development version.
-    
-    let base_result = match compile_with_custom_dep(&rev_dep, &config.base_override) {
+    // Skip rev deps whose requirement on our crate could never resolve
+    // to the in-development version, so we don't report a "regression"
+    // for a dependent that wouldn't pick up the WIP anyway.
+    let req = match semver::VersionReq::parse(&candidate.req) {
+        Ok(r) => r,
+        Err(e) => return TestResult::error(rev_dep, Error::from(e))
+    };
+    if !req.matches(&config.our_version) {
+        let reason = format!("{} requires \"{}\", which does not match in-development version {}",
+                              rev_dep.name, candidate.req, config.our_version);
+        return TestResult::skipped(rev_dep, reason);
+    }
+
+    let base_result = match compile_with_custom_dep(config, &rev_dep, &config.base_override) {
         Ok(r) => r,
         Err(e) => return TestResult::error(rev_dep, e)
     };
 
-    if base_result.failed() {
+    // None of the requested --target triples have an installed std, so
+    // nothing was actually built; report this the same way as any other
+    // skip rather than as a silent, unearned Pass.
+    if all_unavailable(&base_result) {
+        let reason = format!("no installed standard library for any requested target: {}",
+                              config.targets.join(", "));
+        return TestResult::skipped(rev_dep, reason);
+    }
+
+    if any_failed(&base_result) {
         return TestResult::broken(rev_dep, base_result);
     }
-    let next_result = match compile_with_custom_dep(&rev_dep, &config.next_override) {
+    let next_result = match compile_with_custom_dep(config, &rev_dep, &config.next_override) {
         Ok(r) => r,
         Err(e) => return TestResult::error(rev_dep, e)
     };
 
-    if next_result.failed() {
-        TestResult::regressed(rev_dep, base_result, next_result)
+    if any_failed(&next_result) {
+        if config.mode == Mode::Test && only_test_failures(&next_result) {
+            TestResult::test_regressed(rev_dep, base_result, next_result)
+        } else {
+            TestResult::regressed(rev_dep, base_result, next_result)
+        }
     } else {
         TestResult::pass(rev_dep, base_result, next_result)
     }
 }
 
+fn any_failed(results: &[TargetCompileResult]) -> bool {
+    results.iter().any(|r| r.failed())
+}
+
+// True when every target was skipped for lacking an installed std, i.e.
+// nothing was actually compiled, as opposed to compiling cleanly.
+fn all_unavailable(results: &[TargetCompileResult]) -> bool {
+    !results.is_empty() && results.iter().all(|r| r.unavailable)
+}
+
+// True when every failing target still produced a full test summary,
+// i.e. it compiled fine and it was the test suite itself that went red,
+// as opposed to the build breaking outright.
+fn only_test_failures(results: &[TargetCompileResult]) -> bool {
+    results.iter().filter(|r| r.failed()).all(|r| r.tests.is_some())
+}
+
 fn resolve_rev_dep_version(name: RevDepName) -> Result<RevDep, Error> {
     debug!("resolving current version for {}", name);
     let ref url = crate_url(&name, None);
@@ -410,7 +711,48 @@ impl CompileResult {
     }
 }
 
-fn compile_with_custom_dep(rev_dep: &RevDep, krate: &CrateOverride) -> Result<CompileResult, Error> {
+// The result of running `cargo test --no-fail-fast` for a single target,
+// parsed out of its stdout. Only present when `Config::mode` is `Test`.
+#[derive(Debug, Clone)]
+struct TestSummary {
+    passed: u32,
+    failed: u32,
+    failing_tests: Vec<String>
+}
+
+// The result of building (or testing) a rev dep for a single target
+// triple. `target` is `None` for a plain host build (the default when
+// no `--target` was requested). `unavailable` is set when the target
+// was never attempted because the toolchain has no std for it; such a
+// result carries a synthetic `result.success = false` for display
+// purposes only and must not be treated as a real build failure.
+#[derive(Debug, Clone)]
+struct TargetCompileResult {
+    target: Option<String>,
+    result: CompileResult,
+    tests: Option<TestSummary>,
+    unavailable: bool
+}
+
+impl TargetCompileResult {
+    // A real, reportable failure: the build (or test) actually ran and
+    // came back red. Targets skipped for lacking std don't count, since
+    // a missing toolchain component isn't a regression in the rev dep.
+    fn failed(&self) -> bool {
+        !self.unavailable && self.result.failed()
+    }
+
+    fn target_name(&self) -> &str {
+        match self.target {
+            Some(ref t) => t,
+            None => "host"
+        }
+    }
+}
+
+fn compile_with_custom_dep(config: &Config,
+                           rev_dep: &RevDep,
+                           krate: &CrateOverride) -> Result<Vec<TargetCompileResult>, Error> {
     let ref crate_handle = try!(get_crate_handle(rev_dep));
     let temp_dir = try!(TempDir::new("crusader"));
     let ref source_dir = temp_dir.path().join("source");
@@ -426,12 +768,53 @@ fn compile_with_custom_dep(rev_dep: &RevDep, krate: &CrateOverride) -> Result<Co
         }
     }
 
-    // NB: The way cargo searches for .cargo/config, which we use to
-    // override dependencies, depends on the CWD, and is not affacted
-    // by the --manifest-path flag, so this is changing directories.
-    let mut cmd = Command::new("cargo");
-    let cmd = cmd.arg("build")
-        .current_dir(source_dir);
+    let sandbox_image = config.sandbox_image.as_ref().map(|s| &s[..]);
+
+    // No --target flags means just the plain host build, matching the
+    // tool's original behavior.
+    if config.targets.is_empty() {
+        let result = try!(run_cargo(source_dir, None, config.mode, krate, sandbox_image));
+        return Ok(vec![result]);
+    }
+
+    let mut results = Vec::new();
+    for target in &config.targets {
+        if !target_has_std(target, sandbox_image) {
+            status(&format!("skipping target {}, no installed std", target));
+            results.push(TargetCompileResult {
+                target: Some(target.clone()),
+                result: CompileResult {
+                    stdout: String::new(),
+                    stderr: format!("target {} has no installed standard library", target),
+                    success: false
+                },
+                tests: None,
+                unavailable: true
+            });
+            continue;
+        }
+        results.push(try!(run_cargo(source_dir, Some(target), config.mode, krate, sandbox_image)));
+    }
+
+    Ok(results)
+}
+
+// NB: The way cargo searches for .cargo/config, which we use to
+// override dependencies, depends on the CWD, and is not affacted
+// by the --manifest-path flag, so this is changing directories.
+fn run_cargo(source_dir: &Path,
+             target: Option<&String>,
+             mode: Mode,
+             krate: &CrateOverride,
+             sandbox_image: Option<&str>) -> Result<TargetCompileResult, Error> {
+    let mut cmd = cargo_command(source_dir, krate, sandbox_image);
+    let cmd = match mode {
+        Mode::Build => cmd.arg("build"),
+        Mode::Test => cmd.arg("test").arg("--no-fail-fast")
+    };
+    if let Some(target) = target {
+        cmd.arg("--target").arg(target);
+    }
     debug!("running cargo: {:?}", cmd);
     let r = try!(cmd.output());
 
@@ -439,13 +822,170 @@ fn compile_with_custom_dep(rev_dep: &RevDep, krate: &CrateOverride) -> Result<Co
 
     debug!("result: {:?}", success);
 
-    Ok(CompileResult {
-        stdout: try!(String::from_utf8(r.stdout)),
-        stderr: try!(String::from_utf8(r.stderr)),
-        success: success
+    let stdout = try!(String::from_utf8(r.stdout));
+    let stderr = try!(String::from_utf8(r.stderr));
+    let tests = match mode {
+        Mode::Build => None,
+        Mode::Test => parse_test_summary(&stdout)
+    };
+
+    Ok(TargetCompileResult {
+        target: target.cloned(),
+        result: CompileResult {
+            stdout: stdout,
+            stderr: stderr,
+            success: success
+        },
+        tests: tests,
+        unavailable: false
+    })
+}
+
+// Builds the `cargo` invocation, either directly on the host (the
+// default) or wrapped in `docker run` against `--sandbox-image` for a
+// reproducible, isolated build. The unpacked source dir, and the WIP
+// crate path when one is being overridden, are bind-mounted at their
+// host paths so the absolute path baked into .cargo/config by
+// `emit_cargo_override_path` still resolves the same way inside the
+// container.
+fn cargo_command(source_dir: &Path, krate: &CrateOverride, sandbox_image: Option<&str>) -> Command {
+    let image = match sandbox_image {
+        Some(image) => image,
+        None => {
+            let mut cmd = Command::new("cargo");
+            cmd.current_dir(source_dir);
+            return cmd;
+        }
+    };
+
+    let mut cmd = Command::new("docker");
+    cmd.arg("run").arg("--rm")
+        .arg("-v").arg(format!("{}:{}", source_dir.display(), source_dir.display()))
+        .arg("-w").arg(format!("{}", source_dir.display()));
+
+    if let CrateOverride::Source(ref override_path) = *krate {
+        if let Some(override_dir) = override_path.parent() {
+            let override_dir = if override_dir.is_absolute() {
+                override_dir.to_path_buf()
+            } else {
+                env::current_dir().map(|d| d.join(override_dir))
+                    .unwrap_or_else(|_| override_dir.to_path_buf())
+            };
+            cmd.arg("-v").arg(format!("{}:{}", override_dir.display(), override_dir.display()));
+        }
+    }
+
+    cmd.arg(image).arg("cargo");
+    cmd
+}
+
+// Pulls the pass/fail counts and the names of any failing tests out of
+// `cargo test`'s stdout, e.g.:
+//
+//     failures:
+//         foo::bar_baz
+//
+//     test result: FAILED. 3 passed; 1 failed; 0 ignored; 0 measured; 0 filtered out
+// A rev dep with unit tests plus an integration test file (or doctests)
+// produces one "test result:"/"failures:" section per binary, so both
+// counts and names have to be aggregated across all of them rather than
+// read from just the first or last.
+fn parse_test_summary(stdout: &str) -> Option<TestSummary> {
+    let result_positions: Vec<usize> = stdout.match_indices("test result:")
+        .map(|(i, _)| i)
+        .collect();
+    if result_positions.is_empty() {
+        return None;
+    }
+
+    let mut passed = 0;
+    let mut failed = 0;
+    let mut failing_tests = Vec::new();
+    let mut binary_start = 0;
+
+    for pos in result_positions {
+        let result_line = stdout[pos..].lines().next().unwrap_or("");
+        passed += parse_test_count(result_line, "passed");
+        failed += parse_test_count(result_line, "failed");
+
+        // Each binary's section can contain more than one "failures:"
+        // block (a verbose per-test stdout dump, then a plain list of
+        // names just before its "test result:" line); the last one
+        // before this binary's result line is the plain list.
+        let section = &stdout[binary_start..pos];
+        if let Some(idx) = section.rfind("\nfailures:\n") {
+            let rest = &section[idx + "\nfailures:\n".len()..];
+            for line in rest.lines() {
+                let line = line.trim();
+                if !line.is_empty() {
+                    failing_tests.push(line.to_string());
+                }
+            }
+        }
+
+        binary_start = pos;
+    }
+
+    Some(TestSummary {
+        passed: passed,
+        failed: failed,
+        failing_tests: failing_tests
     })
 }
 
+fn parse_test_count(result_line: &str, label: &str) -> u32 {
+    for part in result_line.split(';') {
+        let part = part.trim();
+        if part.ends_with(label) {
+            if let Some(num) = part.split_whitespace().rev().nth(1) {
+                if let Ok(n) = num.parse() {
+                    return n;
+                }
+            }
+        }
+    }
+    0
+}
+
+// Checks whether the toolchain that will actually run the build has a
+// std built for `triple`, so that requesting an uninstalled
+// cross-compilation target degrades into a tagged failure instead of a
+// useless cargo invocation. When `sandbox_image` is set the real build
+// runs inside that container, so the probe has to run there too, rather
+// than against the host's rustc, which may have an entirely different
+// set of installed targets.
+fn target_has_std(triple: &str, sandbox_image: Option<&str>) -> bool {
+    match sandbox_image {
+        Some(image) => target_has_std_sandboxed(triple, image),
+        None => target_has_std_host(triple)
+    }
+}
+
+fn target_has_std_host(triple: &str) -> bool {
+    let sysroot = match Command::new("rustc").arg("--print").arg("sysroot").output() {
+        Ok(ref o) if o.status.success() => {
+            match String::from_utf8(o.stdout.clone()) {
+                Ok(s) => s.trim().to_string(),
+                Err(_) => return false
+            }
+        }
+        _ => return false
+    };
+
+    Path::new(&sysroot).join("lib").join("rustlib").join(triple).join("lib").is_dir()
+}
+
+fn target_has_std_sandboxed(triple: &str, image: &str) -> bool {
+    let probe = format!("test -d \"$(rustc --print sysroot)/lib/rustlib/{}/lib\"", triple);
+    Command::new("docker")
+        .arg("run").arg("--rm")
+        .arg(image)
+        .arg("sh").arg("-c").arg(probe)
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
 struct CrateHandle(PathBuf);
 
 fn get_crate_handle(rev_dep: &RevDep) -> Result<CrateHandle, Error> {
@@ -471,22 +1011,47 @@ fn get_crate_handle(rev_dep: &RevDep) -> Result<CrateHandle, Error> {
 impl CrateHandle {
     fn unpack_source_to(&self, path: &Path) -> Result<(), Error> {
         debug!("unpackng {:?} to {:?}", self.0, path);
-        let mut cmd = Command::new("tar");
-        let cmd = cmd
-            .arg("xzf")
-            .arg(self.0.to_str().unwrap().to_owned())
-            .arg("--strip-components=1")
-            .arg("-C")
-            .arg(path.to_str().unwrap().to_owned());
-        let r = try!(cmd.output());
-        if r.status.success() {
-            Ok(())
-        } else {
-            // FIXME: Want to put r in this value but
-            // process::Output doesn't implement Debug
-            let s = String::from_utf8_lossy(&r.stderr).into_owned();
-            Err(Error::ProcessError(s))
+        let file = try!(File::open(&self.0));
+        let gz = try!(GzDecoder::new(file));
+        let mut archive = Archive::new(gz);
+
+        for entry in try!(archive.entries()) {
+            let mut entry = try!(entry);
+
+            // crates.io tarballs wrap everything in a single
+            // `<name>-<version>/` directory; strip it the way
+            // `tar --strip-components=1` used to.
+            let entry_path = try!(entry.path()).into_owned();
+            let mut components = entry_path.components();
+            components.next();
+            let stripped = components.as_path();
+            if stripped.as_os_str().is_empty() {
+                continue;
+            }
+
+            // Refuse to unpack anything that would escape `path` (tar-slip):
+            // a crafted rev-dep tarball could otherwise write outside the
+            // temp dir via an absolute path or a `..` component. Symlinks
+            // and hardlinks are refused outright too: a link entry like
+            // `foo -> /` followed by an innocuous-looking `foo/bar` entry
+            // would let later entries write through it to outside `path`
+            // without either entry's own path ever containing `..`.
+            let entry_type = entry.header().entry_type();
+            if entry_type.is_symlink() || entry_type.is_hard_link() {
+                return Err(Error::UnsafeTarPath(entry_path));
+            }
+            if stripped.is_absolute() || stripped.components().any(|c| c == Component::ParentDir) {
+                return Err(Error::UnsafeTarPath(entry_path));
+            }
+
+            let dest = path.join(stripped);
+            if let Some(parent) = dest.parent() {
+                try!(fs::create_dir_all(parent));
+            }
+            try!(entry.unpack(&dest));
         }
+
+        Ok(())
     }
 }
 
@@ -522,6 +1087,20 @@ fn status_lock<F>(f: F) where F: FnOnce() -> () {
     f();
 }
 
+// Human-readable progress output is suppressed in JSON mode so it
+// doesn't get interleaved with the machine-readable result stream.
+lazy_static! {
+    static ref QUIET: Mutex<bool> = Mutex::new(false);
+}
+
+fn set_quiet(quiet: bool) {
+    *QUIET.lock().unwrap() = quiet;
+}
+
+fn is_quiet() -> bool {
+    *QUIET.lock().unwrap()
+}
+
 fn print_status_header() {
     print!("crusader: ");
 }
@@ -543,6 +1122,9 @@ fn print_color(s: &str, color: term::color::Color) {
 }
 
 fn status(s: &str) {
+    if is_quiet() {
+        return;
+    }
     status_lock(|| {
         print_status_header();
         println!("{}", s);
@@ -550,6 +1132,9 @@ fn status(s: &str) {
 }
 
 fn report_quick_result(current_num: usize, total: usize, result: &TestResult) {
+    if is_quiet() {
+        return;
+    }
     status_lock(|| {
         print_status_header();
         print!("result {} of {}, {} {}: ",
@@ -560,18 +1145,232 @@ fn report_quick_result(current_num: usize, total: usize, result: &TestResult) {
                );
         print_color(&format!("{}", result.quick_str()),
                     result.term_color());
+        let targets = result.data.regressed_targets();
+        if !targets.is_empty() {
+            print!(" ({})", targets.join(", "));
+        }
+        let tests = result.data.regressed_tests();
+        if !tests.is_empty() {
+            print!(" [{}]", tests.join(", "));
+        }
         println!("");
     });
 }
 
-fn report_results(res: Result<Vec<TestResult>, Error>) {
+fn report_results(message_format: MessageFormat, res: Result<Vec<TestResult>, Error>) {
+    match message_format {
+        MessageFormat::Human => report_results_human(res),
+        MessageFormat::Json => report_results_json(res)
+    }
+}
+
+fn report_results_human(res: Result<Vec<TestResult>, Error>) {
+    if let Ok(ref results) = res {
+        for result in results {
+            report_regression_diff(result);
+        }
+    }
+
     println!("results: {:?}", res);
 }
 
+#[derive(RustcEncodable)]
+struct JsonResult {
+    name: String,
+    version: String,
+    outcome: String,
+    stdout: String,
+    stderr: String
+}
+
+#[derive(RustcEncodable)]
+struct JsonSummary {
+    total: usize,
+    broken: usize,
+    regressed: usize,
+    test_regressed: usize,
+    pass: usize,
+    skipped: usize,
+    error: usize
+}
+
+#[derive(RustcEncodable)]
+struct JsonRunError {
+    error: String
+}
+
+// Emits one JSON object per line for each rev dep result, followed by a
+// final summary object with counts, so a CI pipeline can parse the
+// stream and fail the job only on an actual `regressed` outcome.
+fn report_results_json(res: Result<Vec<TestResult>, Error>) {
+    let results = match res {
+        Ok(r) => r,
+        Err(e) => {
+            let err = JsonRunError { error: format!("{:?}", e) };
+            println!("{}", json::encode(&err).unwrap());
+            return;
+        }
+    };
+
+    let mut summary = JsonSummary {
+        total: results.len(),
+        broken: 0,
+        regressed: 0,
+        test_regressed: 0,
+        pass: 0,
+        skipped: 0,
+        error: 0
+    };
+
+    for result in &results {
+        match result.quick_str() {
+            "broken" => summary.broken += 1,
+            "regressed" => summary.regressed += 1,
+            "test-regressed" => summary.test_regressed += 1,
+            "pass" => summary.pass += 1,
+            "skipped" => summary.skipped += 1,
+            _ => summary.error += 1
+        }
+
+        let (stdout, stderr) = collect_output(result);
+        let record = JsonResult {
+            name: result.rev_dep.name.clone(),
+            version: format!("{}", result.rev_dep.vers),
+            outcome: result.quick_str().to_string(),
+            stdout: stdout,
+            stderr: stderr
+        };
+        println!("{}", json::encode(&record).unwrap());
+    }
+
+    println!("{}", json::encode(&summary).unwrap());
+}
+
+// Concatenates the captured output across every requested target,
+// including the note left for one skipped for lacking std, labeling
+// each section when more than one target was requested.
+fn collect_output(result: &TestResult) -> (String, String) {
+    match result.data {
+        TestResultData::Broken(ref r) => join_target_output(r),
+        TestResultData::Regressed(_, ref next) |
+        TestResultData::TestRegressed(_, ref next) |
+        TestResultData::Pass(_, ref next) => join_target_output(next),
+        TestResultData::Skipped(ref reason) => (String::new(), reason.clone()),
+        TestResultData::Error(ref e) => (String::new(), format!("{:?}", e))
+    }
+}
+
+fn join_target_output(results: &[TargetCompileResult]) -> (String, String) {
+    let mut stdout = String::new();
+    let mut stderr = String::new();
+    for r in results {
+        if results.len() > 1 {
+            stdout.push_str(&format!("--- {} ---\n", r.target_name()));
+            stderr.push_str(&format!("--- {} ---\n", r.target_name()));
+        }
+        stdout.push_str(&r.result.stdout);
+        stderr.push_str(&r.result.stderr);
+    }
+    (stdout, stderr)
+}
+
+// For a regressed result, print a line-level diff of each failing target's base
+// vs. next stderr so it's obvious what the WIP change actually broke,
+// instead of making the user eyeball two walls of compiler output.
+fn report_regression_diff(result: &TestResult) {
+    let (base, next) = match result.data {
+        TestResultData::Regressed(ref base, ref next) |
+        TestResultData::TestRegressed(ref base, ref next) => (base, next),
+        _ => return
+    };
+
+    for (b, n) in base.iter().zip(next.iter()) {
+        if !n.failed() {
+            continue;
+        }
+        println!("crusader: diff for {} ({}):", result.rev_dep.name, n.target_name());
+        print_diff(&b.result.stderr, &n.result.stderr);
+    }
+}
+
+// A line-oriented diff, in the spirit of `diff`/`git diff`: lines only
+// in `old` are removed, lines only in `new` are added, and everything
+// else is shared context.
+#[derive(Debug, Clone, PartialEq)]
+enum DiffLine {
+    Same(String),
+    Added(String),
+    Removed(String)
+}
+
+fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let lcs = lcs_table(&old_lines, &new_lines);
+
+    let mut result = Vec::new();
+    let mut i = old_lines.len();
+    let mut j = new_lines.len();
+    while i > 0 && j > 0 {
+        if old_lines[i - 1] == new_lines[j - 1] {
+            result.push(DiffLine::Same(old_lines[i - 1].to_string()));
+            i -= 1;
+            j -= 1;
+        } else if lcs[i][j - 1] >= lcs[i - 1][j] {
+            // Emitted backward, so taking the added line first on a tie
+            // puts removals ahead of additions once reversed.
+            result.push(DiffLine::Added(new_lines[j - 1].to_string()));
+            j -= 1;
+        } else {
+            result.push(DiffLine::Removed(old_lines[i - 1].to_string()));
+            i -= 1;
+        }
+    }
+    while i > 0 {
+        result.push(DiffLine::Removed(old_lines[i - 1].to_string()));
+        i -= 1;
+    }
+    while j > 0 {
+        result.push(DiffLine::Added(new_lines[j - 1].to_string()));
+        j -= 1;
+    }
+
+    result.reverse();
+    result
+}
+
+// Standard dynamic-programming longest-common-subsequence table: entry
+// `[i][j]` holds the LCS length of `a[..i]` and `b[..j]`, walked
+// backward by `diff_lines` to recover the Same/Added/Removed runs.
+fn lcs_table(a: &[&str], b: &[&str]) -> Vec<Vec<usize>> {
+    let mut table = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for i in 1..a.len() + 1 {
+        for j in 1..b.len() + 1 {
+            table[i][j] = if a[i - 1] == b[j - 1] {
+                table[i - 1][j - 1] + 1
+            } else {
+                cmp::max(table[i - 1][j], table[i][j - 1])
+            };
+        }
+    }
+    table
+}
+
+fn print_diff(old: &str, next: &str) {
+    for line in diff_lines(old, next) {
+        match line {
+            DiffLine::Same(ref l) => print_color(&format!("  {}\n", l), term::color::BRIGHT_BLACK),
+            DiffLine::Removed(ref l) => print_color(&format!("- {}\n", l), term::color::BRIGHT_GREEN),
+            DiffLine::Added(ref l) => print_color(&format!("+ {}\n", l), term::color::BRIGHT_RED)
+        }
+    }
+}
+
 #[derive(Debug)]
 enum Error {
     ManifestName(PathBuf),
     SemverError(semver::ParseError),
+    SemverReqError(semver::ReqParseError),
     TomlError(Vec<toml::ParserError>),
     IoError(io::Error),
     CurlError(curl::ErrCode),
@@ -581,7 +1380,9 @@ enum Error {
     RecvError(RecvError),
     NoCrateVersions,
     FromUtf8Error(FromUtf8Error),
-    ProcessError(String)
+    MissingArgument(String),
+    InvalidArgument(String, String),
+    UnsafeTarPath(PathBuf)
 }
 
 impl From<semver::ParseError> for Error {
@@ -590,6 +1391,12 @@ impl From<semver::ParseError> for Error {
     }
 }
 
+impl From<semver::ReqParseError> for Error {
+    fn from(e: semver::ReqParseError) -> Error {
+        Error::SemverReqError(e)
+    }
+}
+
 impl From<io::Error> for Error {
     fn from(e: io::Error) -> Error {
         Error::IoError(e)
@@ -637,3 +1444,139 @@ impl fmt::Debug for CurlHttpResponseWrapper {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_lines_identical_input_is_all_same() {
+        let diff = diff_lines("a\nb\nc", "a\nb\nc");
+        assert_eq!(diff, vec![
+            DiffLine::Same("a".to_string()),
+            DiffLine::Same("b".to_string()),
+            DiffLine::Same("c".to_string())
+        ]);
+    }
+
+    #[test]
+    fn diff_lines_marks_changed_middle_line() {
+        let diff = diff_lines("a\nb\nc", "a\nx\nc");
+        assert_eq!(diff, vec![
+            DiffLine::Same("a".to_string()),
+            DiffLine::Removed("b".to_string()),
+            DiffLine::Added("x".to_string()),
+            DiffLine::Same("c".to_string())
+        ]);
+    }
+
+    #[test]
+    fn diff_lines_appended_lines_are_added() {
+        let diff = diff_lines("a", "a\nb\nc");
+        assert_eq!(diff, vec![
+            DiffLine::Same("a".to_string()),
+            DiffLine::Added("b".to_string()),
+            DiffLine::Added("c".to_string())
+        ]);
+    }
+
+    #[test]
+    fn lcs_table_length_of_disjoint_sequences_is_zero() {
+        let table = lcs_table(&["a", "b"], &["c", "d"]);
+        assert_eq!(table[2][2], 0);
+    }
+
+    #[test]
+    fn lcs_table_length_of_shared_subsequence() {
+        let table = lcs_table(&["a", "b", "c"], &["x", "b", "c", "y"]);
+        assert_eq!(table[3][4], 2);
+    }
+
+    fn candidate(name: &str, req: &str) -> RevDepCandidate {
+        RevDepCandidate { name: name.to_string(), req: req.to_string() }
+    }
+
+    #[test]
+    fn dedup_rev_deps_keeps_first_occurrence_of_each_name() {
+        let deduped = dedup_rev_deps(vec![
+            candidate("foo", "1.0"),
+            candidate("bar", "2.0"),
+            candidate("foo", "0.5")
+        ]);
+        assert_eq!(deduped.len(), 2);
+        assert_eq!(deduped[0].name, "foo");
+        assert_eq!(deduped[0].req, "1.0");
+        assert_eq!(deduped[1].name, "bar");
+    }
+
+    #[test]
+    fn dedup_rev_deps_preserves_first_seen_order() {
+        let deduped = dedup_rev_deps(vec![
+            candidate("a", "1.0"),
+            candidate("b", "1.0"),
+            candidate("a", "1.0"),
+            candidate("c", "1.0")
+        ]);
+        let names: Vec<&str> = deduped.iter().map(|c| &c.name[..]).collect();
+        assert_eq!(names, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn parse_test_summary_none_when_no_result_line() {
+        assert!(parse_test_summary("running 0 tests\n").is_none());
+    }
+
+    #[test]
+    fn parse_test_summary_single_binary_with_failures() {
+        let stdout = "running 2 tests\n\
+                       test foo::bar_baz ... FAILED\n\
+                       test foo::ok_one ... ok\n\
+                       \n\
+                       failures:\n\
+                       \n\
+                       ---- foo::bar_baz stdout ----\n\
+                       thread 'foo::bar_baz' panicked\n\
+                       \n\
+                       failures:\n\
+                           foo::bar_baz\n\
+                       \n\
+                       test result: FAILED. 1 passed; 1 failed; 0 ignored; 0 measured; 0 filtered out\n";
+        let summary = parse_test_summary(stdout).unwrap();
+        assert_eq!(summary.passed, 1);
+        assert_eq!(summary.failed, 1);
+        assert_eq!(summary.failing_tests, vec!["foo::bar_baz".to_string()]);
+    }
+
+    #[test]
+    fn parse_test_summary_aggregates_across_multiple_binaries() {
+        let stdout = "running 1 test\n\
+                       test unit::ok ... ok\n\
+                       \n\
+                       test result: ok. 1 passed; 0 failed; 0 ignored; 0 measured; 0 filtered out\n\
+                       \n\
+                       running 1 test\n\
+                       test integration::broke ... FAILED\n\
+                       \n\
+                       failures:\n\
+                       \n\
+                       ---- integration::broke stdout ----\n\
+                       assertion failed\n\
+                       \n\
+                       failures:\n\
+                           integration::broke\n\
+                       \n\
+                       test result: FAILED. 0 passed; 1 failed; 0 ignored; 0 measured; 0 filtered out\n";
+        let summary = parse_test_summary(stdout).unwrap();
+        assert_eq!(summary.passed, 1);
+        assert_eq!(summary.failed, 1);
+        assert_eq!(summary.failing_tests, vec!["integration::broke".to_string()]);
+    }
+
+    #[test]
+    fn parse_test_count_reads_label_matching_field() {
+        let line = "test result: FAILED. 3 passed; 1 failed; 0 ignored; 0 measured; 0 filtered out";
+        assert_eq!(parse_test_count(line, "passed"), 3);
+        assert_eq!(parse_test_count(line, "failed"), 1);
+        assert_eq!(parse_test_count(line, "ignored"), 0);
+    }
+}